@@ -0,0 +1,16 @@
+// Copyright: Ankitects Pty Ltd and contributors
+// License: GNU AGPL, version 3 or later; http://www.gnu.org/licenses/agpl.html
+
+mod undo;
+
+pub(crate) use undo::{UndoableChange, UndoManager};
+
+use crate::notetype::NotetypeCache;
+
+/// Mutable, per-open-collection state that lives alongside the collection's
+/// `Collection` handle rather than inside storage.
+#[derive(Default)]
+pub(crate) struct CollectionState {
+    pub(crate) notetype_cache: NotetypeCache,
+    pub(crate) undo: UndoManager,
+}
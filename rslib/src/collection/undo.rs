@@ -0,0 +1,86 @@
+// Copyright: Ankitects Pty Ltd and contributors
+// License: GNU AGPL, version 3 or later; http://www.gnu.org/licenses/agpl.html
+
+use std::collections::VecDeque;
+
+use crate::{collection::Collection, error::Result, notetype::NotetypeUndoEvent};
+
+/// A reversible change recorded by one of the undoable subsystems.
+pub(crate) enum UndoableChange {
+    Notetype(NotetypeUndoEvent),
+}
+
+impl UndoableChange {
+    fn undo(self, col: &mut Collection) -> Result<()> {
+        match self {
+            UndoableChange::Notetype(event) => col.undo_notetype_change(event),
+        }
+    }
+}
+
+enum UndoMode {
+    Normal,
+    Undoing,
+    Redoing,
+}
+
+impl Default for UndoMode {
+    fn default() -> Self {
+        UndoMode::Normal
+    }
+}
+
+/// Tracks undo/redo steps across all undoable subsystems. Subsystems call
+/// [`Collection::save_undo`] from within their mutating entry points; `undo`
+/// and `redo` pop a step and dispatch it back to the subsystem that produced
+/// it, which re-records the reversal as the step needed to go the other way.
+#[derive(Default)]
+pub(crate) struct UndoManager {
+    undo_steps: VecDeque<UndoableChange>,
+    redo_steps: VecDeque<UndoableChange>,
+    mode: UndoMode,
+}
+
+impl UndoManager {
+    fn save(&mut self, change: UndoableChange) {
+        match self.mode {
+            UndoMode::Normal => {
+                self.redo_steps.clear();
+                self.undo_steps.push_back(change);
+            }
+            UndoMode::Undoing => self.redo_steps.push_back(change),
+            UndoMode::Redoing => self.undo_steps.push_back(change),
+        }
+    }
+}
+
+impl Collection {
+    /// Record a reversible change made by one of the undoable subsystems.
+    pub(crate) fn save_undo(&mut self, change: UndoableChange) {
+        self.state.undo.save(change);
+    }
+
+    /// Reverse the most recent undoable change, if any.
+    pub fn undo(&mut self) -> Result<()> {
+        if let Some(change) = self.state.undo.undo_steps.pop_back() {
+            self.state.undo.mode = UndoMode::Undoing;
+            let result = change.undo(self);
+            self.state.undo.mode = UndoMode::Normal;
+            result
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Reapply the most recently undone change, if any.
+    pub fn redo(&mut self) -> Result<()> {
+        if let Some(change) = self.state.undo.redo_steps.pop_back() {
+            self.state.undo.mode = UndoMode::Redoing;
+            let result = change.undo(self);
+            self.state.undo.mode = UndoMode::Normal;
+            result
+        } else {
+            Ok(())
+        }
+    }
+}
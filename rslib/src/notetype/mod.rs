@@ -9,6 +9,7 @@ mod schema11;
 mod schemachange;
 mod stock;
 mod templates;
+mod undo;
 
 use std::{
     collections::{HashMap, HashSet},
@@ -21,6 +22,7 @@ pub(crate) use render::RenderCardOutput;
 pub use schema11::{CardTemplateSchema11, NoteFieldSchema11, NotetypeSchema11};
 pub use stock::all_stock_notetypes;
 pub use templates::CardTemplate;
+pub(crate) use undo::{classify_notetype_update, NotetypeUndoEvent};
 use unicase::UniCase;
 
 pub use crate::backend_proto::{
@@ -53,7 +55,84 @@ pub(crate) const DEFAULT_CSS: &str = include_str!("styling.css");
 pub(crate) const DEFAULT_LATEX_HEADER: &str = include_str!("header.tex");
 pub(crate) const DEFAULT_LATEX_FOOTER: &str = r"\end{document}";
 
-#[derive(Debug, PartialEq)]
+/// Default number of notetypes kept in [`NotetypeCache`] before the
+/// least-frequently-used entry is evicted.
+const NOTETYPE_CACHE_CAPACITY: usize = 100;
+
+struct NotetypeCacheEntry {
+    notetype: Arc<Notetype>,
+    /// Incremented on every cache hit; used to find the least-frequently-used
+    /// entry on eviction.
+    access_count: u64,
+    /// Insertion order, used to break ties between equally-unpopular entries
+    /// by evicting the least-recently-inserted one first.
+    inserted_at: u64,
+}
+
+/// A bounded, frequency-aware cache of loaded note types, keyed by
+/// [`NotetypeId`]. Existing `Arc<Notetype>` clones held by callers remain
+/// valid after their entry is evicted, so eviction is safe to perform
+/// unconditionally on insert.
+pub(crate) struct NotetypeCache {
+    capacity: usize,
+    entries: HashMap<NotetypeId, NotetypeCacheEntry>,
+    next_insertion: u64,
+}
+
+impl NotetypeCache {
+    pub(crate) fn new(capacity: usize) -> Self {
+        NotetypeCache {
+            capacity,
+            entries: HashMap::new(),
+            next_insertion: 0,
+        }
+    }
+
+    pub(crate) fn get(&mut self, ntid: &NotetypeId) -> Option<Arc<Notetype>> {
+        let entry = self.entries.get_mut(ntid)?;
+        entry.access_count += 1;
+        Some(entry.notetype.clone())
+    }
+
+    pub(crate) fn insert(&mut self, ntid: NotetypeId, notetype: Arc<Notetype>) {
+        if !self.entries.contains_key(&ntid) && self.entries.len() >= self.capacity {
+            self.evict_one();
+        }
+        let inserted_at = self.next_insertion;
+        self.next_insertion += 1;
+        self.entries.insert(
+            ntid,
+            NotetypeCacheEntry {
+                notetype,
+                access_count: 0,
+                inserted_at,
+            },
+        );
+    }
+
+    pub(crate) fn remove(&mut self, ntid: &NotetypeId) -> Option<Arc<Notetype>> {
+        self.entries.remove(ntid).map(|entry| entry.notetype)
+    }
+
+    fn evict_one(&mut self) {
+        if let Some(victim) = self
+            .entries
+            .iter()
+            .min_by_key(|(_, entry)| (entry.access_count, entry.inserted_at))
+            .map(|(ntid, _)| *ntid)
+        {
+            self.entries.remove(&victim);
+        }
+    }
+}
+
+impl Default for NotetypeCache {
+    fn default() -> Self {
+        NotetypeCache::new(NOTETYPE_CACHE_CAPACITY)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
 pub struct Notetype {
     pub id: NotetypeId,
     pub name: String,
@@ -386,10 +465,12 @@ impl From<Notetype> for NotetypeProto {
 impl Collection {
     /// Add a new notetype, and allocate it an ID.
     pub fn add_notetype(&mut self, nt: &mut Notetype) -> Result<()> {
-        self.transact_no_undo(|col| {
+        self.transact(|col| {
             let usn = col.usn()?;
             nt.set_modified(usn);
-            col.add_notetype_inner(nt, usn)
+            col.add_notetype_inner(nt, usn)?;
+            col.save_notetype_undo(NotetypeUndoEvent::AddNotetype(nt.id));
+            Ok(())
         })
     }
 
@@ -399,6 +480,26 @@ impl Collection {
         self.storage.add_new_notetype(nt)
     }
 
+    /// Clone `ntid` into a new, independent notetype so its fields/templates
+    /// can be experimented with without affecting the original or its notes.
+    pub fn duplicate_notetype(&mut self, ntid: NotetypeId) -> Result<NotetypeId> {
+        let source = self.get_notetype(ntid)?.ok_or(AnkiError::NotFound)?;
+        let mut nt = (*source).clone();
+        nt.id = NotetypeId(0);
+        // ensure_notetype_name_unique() below only disambiguates on
+        // collision, so mark this one as distinct from its source up front,
+        // reusing the same "+" convention.
+        nt.name += "+";
+        self.transact(|col| {
+            let usn = col.usn()?;
+            nt.set_modified(usn);
+            col.add_notetype_inner(&mut nt, usn)?;
+            col.save_notetype_undo(NotetypeUndoEvent::AddNotetype(nt.id));
+            Ok(())
+        })?;
+        Ok(nt.id)
+    }
+
     pub(crate) fn ensure_notetype_name_unique(
         &self,
         notetype: &mut Notetype,
@@ -425,7 +526,7 @@ impl Collection {
         let existing = self.get_notetype(nt.id)?;
         let norm = self.get_bool(BoolKey::NormalizeNoteText);
         nt.prepare_for_update(existing.as_ref().map(AsRef::as_ref))?;
-        self.transact_no_undo(|col| {
+        self.transact(|col| {
             if let Some(existing_notetype) = existing {
                 if existing_notetype.mtime_secs > nt.mtime_secs {
                     return Err(AnkiError::invalid_input("attempt to save stale notetype"));
@@ -437,6 +538,9 @@ impl Collection {
                     norm,
                 )?;
                 col.update_cards_for_changed_templates(nt, existing_notetype.templates.len())?;
+
+                let before = (*existing_notetype).clone();
+                col.save_notetype_undo(classify_notetype_update(before, nt));
             }
 
             let usn = col.usn()?;
@@ -450,8 +554,9 @@ impl Collection {
             col.storage
                 .update_notetype_templates(nt.id, &nt.templates)?;
 
-            // fixme: update cache instead of clearing
-            col.state.notetype_cache.remove(&nt.id);
+            // refresh rather than drop the cached entry, so the next
+            // get_notetype() is served without a storage round-trip
+            col.state.notetype_cache.insert(nt.id, Arc::new(nt.clone()));
 
             Ok(())
         })
@@ -467,7 +572,7 @@ impl Collection {
 
     pub fn get_notetype(&mut self, ntid: NotetypeId) -> Result<Option<Arc<Notetype>>> {
         if let Some(nt) = self.state.notetype_cache.get(&ntid) {
-            return Ok(Some(nt.clone()));
+            return Ok(Some(nt));
         }
         if let Some(nt) = self.storage.get_notetype(ntid)? {
             let nt = Arc::new(nt);
@@ -492,11 +597,19 @@ impl Collection {
     }
 
     pub fn remove_notetype(&mut self, ntid: NotetypeId) -> Result<()> {
-        // fixme: currently the storage layer is taking care of removing the notes and cards,
-        // but we need to do it in this layer in the future for undo handling
-        self.transact_no_undo(|col| {
+        // fixme: the storage layer removes this notetype's notes and cards as
+        // part of `storage.remove_notetype` below, without surfacing what it
+        // deleted, so there's nothing here to capture for undo. Undoing a
+        // removal therefore only restores the notetype row itself (see
+        // `NotetypeUndoEvent::RemoveNotetype`); making the whole removal
+        // atomic for undo purposes needs the storage layer to expose the
+        // deleted notes/cards before they're gone.
+        self.transact(|col| {
             col.set_schema_modified()?;
             col.state.notetype_cache.remove(&ntid);
+            if let Some(nt) = col.storage.get_notetype(ntid)? {
+                col.save_notetype_undo(NotetypeUndoEvent::RemoveNotetype(Box::new(nt)));
+            }
             col.clear_aux_config_for_notetype(ntid)?;
             col.storage.remove_notetype(ntid)?;
             let all = col.storage.get_all_notetype_names()?;
@@ -510,3 +623,37 @@ impl Collection {
         })
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn lfu_eviction_drops_least_used_entry() {
+        let mut cache = NotetypeCache::new(2);
+        cache.insert(NotetypeId(1), Arc::new(Notetype::default()));
+        cache.insert(NotetypeId(2), Arc::new(Notetype::default()));
+        // bump id 2's access count so id 1 becomes the least-frequently-used entry
+        cache.get(&NotetypeId(2));
+        cache.get(&NotetypeId(2));
+
+        cache.insert(NotetypeId(3), Arc::new(Notetype::default()));
+
+        assert!(cache.get(&NotetypeId(1)).is_none());
+        assert!(cache.get(&NotetypeId(2)).is_some());
+        assert!(cache.get(&NotetypeId(3)).is_some());
+    }
+
+    #[test]
+    fn eviction_ties_broken_by_oldest_insertion() {
+        let mut cache = NotetypeCache::new(2);
+        cache.insert(NotetypeId(1), Arc::new(Notetype::default()));
+        cache.insert(NotetypeId(2), Arc::new(Notetype::default()));
+        // neither entry has been accessed, so the oldest one (id 1) is evicted
+        cache.insert(NotetypeId(3), Arc::new(Notetype::default()));
+
+        assert!(cache.get(&NotetypeId(1)).is_none());
+        assert!(cache.get(&NotetypeId(2)).is_some());
+        assert!(cache.get(&NotetypeId(3)).is_some());
+    }
+}
@@ -0,0 +1,191 @@
+// Copyright: Ankitects Pty Ltd and contributors
+// License: GNU AGPL, version 3 or later; http://www.gnu.org/licenses/agpl.html
+
+use super::{Notetype, NotetypeId};
+use crate::{
+    collection::{Collection, UndoableChange},
+    error::Result,
+    timestamp::TimestampSecs,
+};
+
+/// A single undoable note-type mutation, recorded on the collection's shared
+/// undo stack (see `crate::collection::undo`) before the corresponding
+/// notetype write is made; `AddNotetype` is recorded after the write, once
+/// the new id is known.
+///
+/// `AddNotetype` carries the new id; every other variant carries the
+/// `Notetype` as it was before the mutation, which is enough to reverse it:
+/// reversing replays the mutation that produced
+/// `before` through the normal, undo-recording entry points, so the reversal
+/// itself is pushed onto the stack as the event needed to redo the original
+/// change.
+pub(crate) enum NotetypeUndoEvent {
+    /// The notetype was newly added; undoing removes it again.
+    AddNotetype(NotetypeId),
+    /// Neither `fields` nor `templates` changed; covers `config` (e.g. sort
+    /// field, CSS, latex headers) and name changes.
+    UpdateNotetypeConfig(Box<Notetype>),
+    /// `fields` changed; notes were updated to match via
+    /// `update_notes_for_changed_fields`.
+    UpdateNotetypeFields(Box<Notetype>),
+    /// `templates` changed; cards were updated to match via
+    /// `update_cards_for_changed_templates`.
+    UpdateNotetypeTemplates(Box<Notetype>),
+    /// The notetype was removed; undoing adds it back as a new notetype with
+    /// a freshly allocated id.
+    ///
+    /// Only the notetype definition is captured here. The notes/cards the
+    /// storage layer deletes alongside it are not recaptured, so undoing a
+    /// removal does not restore them — see the fixme in
+    /// `Collection::remove_notetype`.
+    RemoveNotetype(Box<Notetype>),
+}
+
+impl NotetypeUndoEvent {
+    /// The id of the notetype this event applies to.
+    #[cfg(test)]
+    fn notetype_id(&self) -> NotetypeId {
+        match self {
+            NotetypeUndoEvent::AddNotetype(ntid) => *ntid,
+            NotetypeUndoEvent::UpdateNotetypeConfig(before)
+            | NotetypeUndoEvent::UpdateNotetypeFields(before)
+            | NotetypeUndoEvent::UpdateNotetypeTemplates(before)
+            | NotetypeUndoEvent::RemoveNotetype(before) => before.id,
+        }
+    }
+}
+
+/// Classify a saved notetype update by the most significant thing that
+/// changed, so undoing it reverses the right storage/cache side effects.
+pub(crate) fn classify_notetype_update(before: Notetype, after: &Notetype) -> NotetypeUndoEvent {
+    if before.templates != after.templates {
+        NotetypeUndoEvent::UpdateNotetypeTemplates(Box::new(before))
+    } else if before.fields != after.fields {
+        NotetypeUndoEvent::UpdateNotetypeFields(Box::new(before))
+    } else {
+        NotetypeUndoEvent::UpdateNotetypeConfig(Box::new(before))
+    }
+}
+
+/// `before` was saved prior to a storage write that has since moved the
+/// stored notetype's `mtime_secs` forward, so replaying it as-is would trip
+/// `update_notetype`'s staleness guard. Stamp it with the current time so
+/// the replay is accepted like any other edit.
+fn refresh_mtime_for_replay(mut before: Notetype) -> Notetype {
+    before.mtime_secs = TimestampSecs::now();
+    before
+}
+
+/// `before` still carries the id it had before its notetype was removed, but
+/// `add_notetype`/`add_notetype_inner` expect a fresh, unallocated id.
+fn reset_id_for_readd(mut before: Notetype) -> Notetype {
+    before.id = NotetypeId(0);
+    before
+}
+
+impl Collection {
+    pub(crate) fn save_notetype_undo(&mut self, event: NotetypeUndoEvent) {
+        self.save_undo(UndoableChange::Notetype(event));
+    }
+
+    /// Reverse a previously recorded note-type mutation.
+    pub(crate) fn undo_notetype_change(&mut self, event: NotetypeUndoEvent) -> Result<()> {
+        match event {
+            NotetypeUndoEvent::AddNotetype(ntid) => self.remove_notetype(ntid),
+            NotetypeUndoEvent::UpdateNotetypeConfig(before)
+            | NotetypeUndoEvent::UpdateNotetypeFields(before)
+            | NotetypeUndoEvent::UpdateNotetypeTemplates(before) => {
+                let mut before = refresh_mtime_for_replay(*before);
+                self.update_notetype(&mut before, false)
+            }
+            NotetypeUndoEvent::RemoveNotetype(before) => {
+                let mut before = reset_id_for_readd(*before);
+                self.add_notetype(&mut before)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn sample_notetype(id: i64) -> Notetype {
+        let mut nt = Notetype::default();
+        nt.id = NotetypeId(id);
+        nt.add_field("Front");
+        nt.add_template("Card 1", "{{Front}}", "{{Back}}");
+        nt
+    }
+
+    #[test]
+    fn add_notetype_event_reports_its_id() {
+        let event = NotetypeUndoEvent::AddNotetype(NotetypeId(7));
+        assert_eq!(event.notetype_id(), NotetypeId(7));
+    }
+
+    #[test]
+    fn remove_notetype_event_reports_its_id() {
+        let event = NotetypeUndoEvent::RemoveNotetype(Box::new(sample_notetype(7)));
+        assert_eq!(event.notetype_id(), NotetypeId(7));
+    }
+
+    #[test]
+    fn update_notetype_config_event_reports_its_id() {
+        let event = NotetypeUndoEvent::UpdateNotetypeConfig(Box::new(sample_notetype(7)));
+        assert_eq!(event.notetype_id(), NotetypeId(7));
+    }
+
+    #[test]
+    fn update_notetype_fields_event_reports_its_id() {
+        let event = NotetypeUndoEvent::UpdateNotetypeFields(Box::new(sample_notetype(7)));
+        assert_eq!(event.notetype_id(), NotetypeId(7));
+    }
+
+    #[test]
+    fn update_notetype_templates_event_reports_its_id() {
+        let event = NotetypeUndoEvent::UpdateNotetypeTemplates(Box::new(sample_notetype(7)));
+        assert_eq!(event.notetype_id(), NotetypeId(7));
+    }
+
+    #[test]
+    fn classify_update_picks_most_specific_variant() {
+        let before = sample_notetype(1);
+
+        let mut templates_changed = before.clone();
+        templates_changed.add_template("Card 2", "{{Front}}", "{{Back}}");
+        assert!(matches!(
+            classify_notetype_update(before.clone(), &templates_changed),
+            NotetypeUndoEvent::UpdateNotetypeTemplates(_)
+        ));
+
+        let mut fields_changed = before.clone();
+        fields_changed.add_field("Extra");
+        assert!(matches!(
+            classify_notetype_update(before.clone(), &fields_changed),
+            NotetypeUndoEvent::UpdateNotetypeFields(_)
+        ));
+
+        let mut config_changed = before.clone();
+        config_changed.config.css = "body {}".into();
+        assert!(matches!(
+            classify_notetype_update(before.clone(), &config_changed),
+            NotetypeUndoEvent::UpdateNotetypeConfig(_)
+        ));
+    }
+
+    #[test]
+    fn refresh_mtime_for_replay_advances_past_the_original_timestamp() {
+        let mut nt = sample_notetype(1);
+        nt.mtime_secs = TimestampSecs(1);
+        let refreshed = refresh_mtime_for_replay(nt);
+        assert!(refreshed.mtime_secs.0 > 1);
+    }
+
+    #[test]
+    fn reset_id_for_readd_clears_the_id() {
+        let nt = sample_notetype(42);
+        let reset = reset_id_for_readd(nt);
+        assert_eq!(reset.id, NotetypeId(0));
+    }
+}